@@ -1,12 +1,55 @@
+use std::cell::RefCell;
+use std::convert::TryFrom;
 use std::ffi::{CString, CStr, c_void};
+use std::future::Future;
 use std::marker::PhantomData;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::mem;
+use std::pin::Pin;
+use std::ptr;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::Duration;
 
 use failure::Fail;
 use log::trace;
 
+/// The single running interpreter, if any, recorded so that the native
+/// dispatch trampoline installed by [`RebEngine::register`] can recover an
+/// `&RebEngine` without threading it through Ren-C's C call frame. Sound
+/// because of the same one-interpreter-per-thread invariant `REB_STARTED_UP`
+/// already enforces, and because [`RebEngine::new`] only ever hands out a
+/// heap-boxed engine, so the address stored here outlives any later move of
+/// that `Box`.
+static CURRENT_ENGINE: AtomicPtr<RebEngine> = AtomicPtr::new(ptr::null_mut());
+
+/// The shape of a closure registered with [`RebEngine::register`]: given
+/// the live engine and its marshalled arguments (both borrowed for exactly
+/// the call's lifetime `'r`), it produces a `RebValue` of that same
+/// lifetime to return to Ren-C.
+type RebNativeFn = dyn for<'r> Fn(&'r RebEngine, &[RebValue<'r>]) -> RebValue<'r>;
+
+/// A Rust closure registered with [`RebEngine::register`], boxed twice so
+/// the fat `dyn Fn` pointer can be leaked as a thin `*mut c_void` context
+/// for Ren-C's native dispatch, and the argument names parsed once up front
+/// so the trampoline knows what to pull out of the call frame.
+struct RebNative {
+    f: Box<RebNativeFn>,
+    arg_names: Vec<CString>,
+}
+
 #[derive(Debug)]
-pub struct RebEngine ();
+pub struct RebEngine {
+    natives: RefCell<Vec<*mut RebNative>>,
+    original_write_stdout: RefCell<Option<*mut renc_sys::Reb_Value>>,
+    /// The native currently bound to `write-stdout` by [`RebEngine::on_output`],
+    /// if any, so a later `on_output` call can free the one it supersedes
+    /// instead of leaking it for the engine's lifetime.
+    active_output_native: RefCell<Option<*mut RebNative>>,
+}
 
 #[derive(Debug)]
 pub struct RebValue<'a> {
@@ -23,16 +66,67 @@ pub struct RebErrorValue<'a> {
     engine: PhantomData<&'a RebEngine>,
 }
 
+/// The category a Rebol error belongs to, taken from the error object's
+/// `type` field (e.g. `math`, `syntax`, `access`). Lets a caller match on
+/// `RebErrorType::Math` vs `RebErrorType::Syntax` instead of string-comparing
+/// `RebError::RebError { type_, .. }`, while `Other` keeps any category Ren-C
+/// introduces that this enum doesn't know about yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RebErrorType {
     Math,
+    Script,
+    Syntax,
+    Access,
+    Command,
+    Resource,
+    Internal,
+    User,
+    /// Evaluation was halted by [`RebEngine::eval_with_timeout`]'s watchdog
+    /// rather than by anything the evaluated code itself raised, carrying
+    /// the `rebTick` count elapsed before the halt took effect.
+    Interrupted(usize),
+    Other(String),
+}
+
+impl RebErrorType {
+    fn from_spelling(s: &str) -> Self {
+        match s {
+            "math" => RebErrorType::Math,
+            "script" => RebErrorType::Script,
+            "syntax" => RebErrorType::Syntax,
+            "access" => RebErrorType::Access,
+            "command" => RebErrorType::Command,
+            "resource" => RebErrorType::Resource,
+            "internal" => RebErrorType::Internal,
+            "user" => RebErrorType::User,
+            other => RebErrorType::Other(other.to_owned()),
+        }
+    }
+}
+
+impl std::fmt::Display for RebErrorType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RebErrorType::Math => write!(f, "math"),
+            RebErrorType::Script => write!(f, "script"),
+            RebErrorType::Syntax => write!(f, "syntax"),
+            RebErrorType::Access => write!(f, "access"),
+            RebErrorType::Command => write!(f, "command"),
+            RebErrorType::Resource => write!(f, "resource"),
+            RebErrorType::Internal => write!(f, "internal"),
+            RebErrorType::User => write!(f, "user"),
+            RebErrorType::Interrupted(ticks) => write!(f, "interrupted after {} ticks", ticks),
+            RebErrorType::Other(s) => write!(f, "{}", s),
+        }
+    }
 }
 
 #[derive(Debug, Fail)]
 pub enum RebError {
-    #[fail(display = "Rebol Error, type: {}, id: {}, message: {}", type_, id, message)]
+    #[fail(display = "Rebol Error, type: {}, id: {}, message: {} (near: {}, where: {}, {}:{})",
+        type_, id, message, near, where_, file, line)]
     RebError {
-        //type_: RebErrorType,
-        type_: String,
+        type_: RebErrorType,
         id: String,
         message: String,
         near: String,
@@ -44,14 +138,19 @@ pub enum RebError {
 
 impl RebError {
     fn from_rebval(e: &RebValue) -> Self {
+        let type_spelling: String = e.engine.map_field(e, "type", |v| unsafe {v.unbox_string_q()});
         RebError::RebError {
-            type_: e.engine.map_field(e, "type", |v| unsafe {v.unbox_string_q()}),
+            type_: RebErrorType::from_spelling(&type_spelling),
             id: e.engine.map_field(e, "id", |v| unsafe {v.unbox_string_q()}),
             message: e.engine.map_field(e, "message", |v| unsafe {v.unbox_string()}),
-            near: String::new(),
-            where_: String::new(),
+            // `near` and `where` are BLOCK!s (a source snippet and a call
+            // stack trace, respectively) in Ren-C's standard error object,
+            // not TEXT!, so they're rendered with `mold` rather than
+            // unboxed as a string.
+            near: e.engine.map_field(e, "near", |v| e.engine.mold(v)),
+            where_: e.engine.map_field(e, "where", |v| e.engine.mold(v)),
             file: e.engine.map_field(e, "file", |v| unsafe {v.unbox_string_q()}),
-            line: 0,
+            line: e.engine.map_field(e, "line", |v| v.unbox_integer() as u32),
         }
     }
 }
@@ -60,9 +159,54 @@ pub trait RebCode {
     fn as_const_ptr(&self) -> *const c_void;
 }
 
-static REB_END: [u8; 2] = [0x80, 0x00];
+#[doc(hidden)]
+pub use renc_sys;
+
+#[doc(hidden)]
+pub static REB_END: [u8; 2] = [0x80, 0x00];
 static REB_STARTED_UP: AtomicBool = AtomicBool::new(false);
 
+/// Evaluates any number of `&dyn RebCode` fragments (a mix of [`CUtf8`]
+/// source snippets and [`RebValue`]s) as a single `rebValue` call, wrapped
+/// in `entrap […]` so a raised error comes back as `Err(RebError)` instead
+/// of panicking, with the usual `block?` → `first` unwrapping applied to
+/// the trapped result.
+///
+/// This replaces the old fixed-arity `value1`/`value2`/`value3` methods,
+/// e.g. `reb_value!(engine; fib, engine.integer(n))`.
+#[macro_export]
+macro_rules! reb_value {
+    ($engine:expr; $($frag:expr),+ $(,)?) => {{
+        let __entrap_open = $crate::CUtf8::new("entrap [");
+        let __entrap_close = $crate::CUtf8::new("]");
+        let __trapped = unsafe {
+            $crate::renc_sys::rebValue(
+                __entrap_open.as_const_ptr(),
+                $($frag.as_const_ptr(),)+
+                __entrap_close.as_const_ptr(),
+                $crate::REB_END.as_ptr(),
+            )
+        };
+        $engine.unwrap_trapped(__trapped)
+    }};
+}
+
+/// Like [`reb_value!`], but evaluates for side effects only and discards
+/// the result, mirroring [`RebEngine::elide`] for an arbitrary number of
+/// fragments.
+#[macro_export]
+macro_rules! reb_elide {
+    ($engine:expr; $($frag:expr),+ $(,)?) => {{
+        let _ = &$engine;
+        unsafe {
+            $crate::renc_sys::rebElide(
+                $($frag.as_const_ptr(),)+
+                $crate::REB_END.as_ptr(),
+            )
+        }
+    }};
+}
+
 #[repr(transparent)]
 pub struct CUtf8 {
     s: CString,
@@ -77,12 +221,25 @@ impl CUtf8 {
 }
 
 impl<'a, 'b> RebEngine {
-    pub fn new() -> Self {
+    /// Starts up the interpreter and returns it heap-allocated. This isn't
+    /// just `Box` for its own sake: [`RebEngine::register`] records a raw
+    /// `*mut RebEngine` in `CURRENT_ENGINE` for [`RebEngine::native_trampoline`]
+    /// to dereference later, so the engine's address has to stay fixed for as
+    /// long as any native is registered. A `Box` guarantees that — moving
+    /// the `Box` around (into a `Vec`, out of a builder function, etc.)
+    /// only copies the pointer, never the heap allocation it points at —
+    /// whereas a bare stack-allocated `RebEngine` would leave that pointer
+    /// dangling the moment it was moved.
+    pub fn new() -> Box<Self> {
         if REB_STARTED_UP.compare_and_swap(false, true, Ordering::SeqCst) {
             panic!("Another thread is already running the renc engine");
         }
         unsafe{renc_sys::rebStartup();}
-        Self {}
+        Box::new(Self {
+            natives: RefCell::new(Vec::new()),
+            original_write_stdout: RefCell::new(None),
+            active_output_native: RefCell::new(None),
+        })
     }
 
     pub fn tick(&self) -> usize {
@@ -124,14 +281,26 @@ impl<'a, 'b> RebEngine {
         }
     }
 
-    /*
-    pub fn sized_binary<'a, 'b, T: Into<&'b [u8]>>(&'a self, v: T, len: usize) -> RebValue {
+    pub fn text(&self, v: &str) -> RebValue {
+        unsafe {
+            RebValue::from_raw(self,
+                renc_sys::rebText(CUtf8::new(v).as_const_ptr()))
+        }
+    }
+
+    pub fn null(&self) -> RebValue {
+        unsafe {
+            RebValue::from_raw(self,
+                renc_sys::rebNull())
+        }
+    }
+
+    pub fn sized_binary(&self, v: &[u8]) -> RebValue {
         unsafe {
             RebValue::from_raw(self,
-                               renc_sys::rebSizedBinary(v.into().as_ptr() as *const c_void, len))
+                renc_sys::rebSizedBinary(v.as_ptr() as *const c_void, v.len()))
         }
     }
-    */
 
     pub fn load(&self, code: &str) -> Result<RebValue, RebError>
     {
@@ -169,43 +338,23 @@ impl<'a, 'b> RebEngine {
         f(&v)
     }
 
-    pub fn value1<A>(&'a self, a: &'b A) -> Result<RebValue<'a>, RebError>
-    where
-        A: RebCode
-    {
-        /*
-        let v = unsafe {renc_sys::rebValueQ(a.as_const_ptr(),
-                REB_END.as_ptr())};
-
-        let is_error =  unsafe {
-            renc_sys::rebDid(CUtf8::new("error?").as_const_ptr(),
-                v,
-                REB_END.as_ptr())
-        };
-        if is_error {
-            return Err(RebError::from_rebval(unsafe {
-                &RebValue::from_raw(self, v)
-            }));
-        }
-        */
-
-        let trapped = unsafe {
-            renc_sys::rebValue(
-                CUtf8::new("entrap [").as_const_ptr(),
-                a.as_const_ptr(),
-                CUtf8::new("]").as_const_ptr(),
-                REB_END.as_ptr())
+    /// Renders `v` to its textual Rebol source representation, the way
+    /// `mold` does in-language. Used as the catch-all by
+    /// [`RebWorker`] when handing a result across its channel as an
+    /// [`OwnedRebResult::Molded`] for datatypes it doesn't special-case.
+    pub fn mold(&self, v: &RebValue) -> String {
+        let molded = unsafe {
+            RebValue::from_raw(self,
+                renc_sys::rebValue(
+                    CUtf8::new("mold").as_const_ptr(),
+                    v.inner,
+                    REB_END.as_ptr()))
         };
+        unsafe {molded.unbox_string()}
+    }
 
-        //unsafe {renc_sys::rebRelease(v);}
-        /*
-        unsafe {
-            renc_sys::rebElide(
-                CUtf8::new("print mold").as_const_ptr(),
-                trapped,
-                REB_END.as_ptr());
-        }
-        */
+    #[doc(hidden)]
+    pub fn unwrap_trapped(&'a self, trapped: *mut renc_sys::Reb_Value) -> Result<RebValue<'a>, RebError> {
         let is_error = unsafe {
             renc_sys::rebDid(
                 CUtf8::new("error?").as_const_ptr(),
@@ -213,19 +362,8 @@ impl<'a, 'b> RebEngine {
                 REB_END.as_ptr())
         };
         if is_error {
-            /*
-            unsafe {
-                renc_sys::rebElide(
-                    CUtf8::new("print mold").as_const_ptr(),
-                    trapped,
-                    REB_END.as_ptr());
-            }
-            */
-
             let trapped = unsafe {RebValue::from_raw(self, trapped)};
-
-            let e = RebError::from_rebval(&trapped);
-            return Err(e);
+            Err(RebError::from_rebval(&trapped))
         } else {
             let is_block = unsafe {
                 renc_sys::rebDid(
@@ -243,92 +381,278 @@ impl<'a, 'b> RebEngine {
                 unsafe {
                     renc_sys::rebRelease(trapped);
                 }
-                Ok(RebValue {
-                    inner,
-                    engine: self
-                })
+                Ok(unsafe {RebValue::from_raw(self, inner)})
             } else {
-                Ok(RebValue {
-                    inner: trapped,
-                    engine: self
-                })
+                Ok(unsafe {RebValue::from_raw(self, trapped)})
             }
         }
     }
 
-    pub fn value2<A, B>(&self, a: &A, b: &B) -> Result<RebValue, RebValue>
+    #[deprecated(note = "use the reb_value! macro instead, which accepts any number of fragments")]
+    pub fn value1<A>(&'a self, a: &'b A) -> Result<RebValue<'a>, RebError>
+    where
+        A: RebCode
+    {
+        reb_value!(self; a)
+    }
+
+    #[deprecated(note = "use the reb_value! macro instead, which accepts any number of fragments")]
+    pub fn value2<A, B>(&'a self, a: &'b A, b: &'b B) -> Result<RebValue<'a>, RebError>
+    where
+        A: RebCode,
+        B: RebCode,
+    {
+        reb_value!(self; a, b)
+    }
+
+    #[deprecated(note = "use the reb_value! macro instead, which accepts any number of fragments")]
+    pub fn value3<A, B, C>(&'a self, a: &'b A, b: &'b B, c: &'b C) -> Result<RebValue<'a>, RebError>
     where
         A: RebCode,
         B: RebCode,
+        C: RebCode,
     {
-        let entrap = CUtf8::new("entrap [");
-        let bracket = CUtf8::new("]");
+        reb_value!(self; a, b, c)
+    }
+
+    pub fn elide<T: RebCode>(&self, t: &T) {
+        unsafe {renc_sys::rebElide(t.as_const_ptr(), REB_END.as_ptr())};
+    }
+
+    /// Evaluates `code`, arming a watchdog that raises Ren-C's halt signal
+    /// (`rebHalt`) from a separate timer thread if `timeout` elapses before
+    /// evaluation finishes, so a runaway or malicious script can't hang the
+    /// host indefinitely.
+    ///
+    /// The timer thread only ever raises the halt flag; it never touches
+    /// any `RebValue`, preserving the single-interpreter-per-thread
+    /// invariant. Completion is signaled to the watchdog over a channel
+    /// rather than racing a `sleep` against a separate atomic flag, so
+    /// there's no window where the watchdog can decide to halt just as
+    /// evaluation is finishing on its own: either the "done" message is
+    /// observed before the timeout elapses, or the timeout elapses first
+    /// and `rebHalt` fires — never both. The watchdog is always joined
+    /// before returning, so it can never halt a later, unrelated
+    /// evaluation on this engine.
+    pub fn eval_with_timeout(&self, code: &str, timeout: Duration) -> Result<RebValue, RebError> {
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        let start_tick = self.tick();
+
+        let watchdog = thread::spawn(move || {
+            if done_rx.recv_timeout(timeout).is_err() {
+                unsafe {renc_sys::rebHalt();}
+            }
+        });
+
+        let code = CUtf8::new(code);
+        let entrap_open = CUtf8::new("entrap [");
+        let entrap_close = CUtf8::new("]");
         let trapped = unsafe {
-            renc_sys::rebValue(entrap.as_const_ptr(),
-                a.as_const_ptr(),
-                b.as_const_ptr(),
-                bracket.as_const_ptr(),
+            renc_sys::rebValue(
+                entrap_open.as_const_ptr(),
+                code.as_const_ptr(),
+                entrap_close.as_const_ptr(),
                 REB_END.as_ptr())
         };
-        let error_check = CUtf8::new("error?");
-        let is_error = unsafe {
-            renc_sys::rebDid(error_check.as_const_ptr(),
-                trapped,
+
+        let _ = done_tx.send(());
+        watchdog.join().expect("timeout watchdog thread panicked");
+
+        if trapped.is_null() {
+            let elapsed = self.tick().saturating_sub(start_tick);
+            return Err(RebError::RebError {
+                type_: RebErrorType::Interrupted(elapsed),
+                id: "halt".to_owned(),
+                message: format!("evaluation halted after timing out ({} ticks elapsed)", elapsed),
+                near: String::new(),
+                where_: String::new(),
+                file: String::new(),
+                line: 0,
+            });
+        }
+
+        self.unwrap_trapped(trapped)
+    }
+
+    /// Makes a Rust closure callable from inside evaluated Rebol code,
+    /// binding it to `name` with the Rebol argument spec `spec` (e.g.
+    /// `"[n [integer!]]"`). The closure is called with the live engine and
+    /// the marshalled arguments each time the Rebol word is invoked, and is
+    /// kept alive until this engine is dropped.
+    pub fn register<F>(&'a self, name: &str, spec: &str, f: F)
+    where
+        F: for<'r> Fn(&'r RebEngine, &[RebValue<'r>]) -> RebValue<'r> + 'static,
+    {
+        self.register_native(name, spec, f);
+    }
+
+    /// Does the actual work of [`RebEngine::register`], additionally
+    /// returning the raw `RebNative` it installed so callers that replace
+    /// their own registration later (like [`RebEngine::on_output`]) can
+    /// free the superseded one instead of leaking it.
+    fn register_native<F>(&'a self, name: &str, spec: &str, f: F) -> *mut RebNative
+    where
+        F: for<'r> Fn(&'r RebEngine, &[RebValue<'r>]) -> RebValue<'r> + 'static,
+    {
+        CURRENT_ENGINE.store(self as *const RebEngine as *mut RebEngine, Ordering::SeqCst);
+
+        let native = Box::into_raw(Box::new(RebNative {
+            f: Box::new(f),
+            arg_names: Self::parse_arg_names(spec),
+        }));
+        self.natives.borrow_mut().push(native);
+
+        let func = unsafe {
+            renc_sys::rebFunction(
+                Self::native_trampoline,
+                native as *mut c_void,
+                CUtf8::new(spec).as_const_ptr(),
                 REB_END.as_ptr())
         };
-        if is_error {
-            Err(RebValue {
-                inner: trapped,
-                engine: self
-            })
-        } else {
-            let block_check = CUtf8::new("block?");
-            let is_block = unsafe {
-                renc_sys::rebDid(block_check.as_const_ptr(),
-                    trapped,
-                    REB_END.as_ptr())
-            };
-            if is_block {
-                let first = CUtf8::new("first");
-                let inner = unsafe {
-                    renc_sys::rebValue(first.as_const_ptr(),
-                        trapped,
-                        REB_END.as_ptr())
-                };
-                unsafe {
-                    renc_sys::rebRelease(trapped);
+        unsafe {
+            renc_sys::rebElide(
+                CUtf8::new("set").as_const_ptr(),
+                CUtf8::new(&format!("'{}", name)).as_const_ptr(),
+                func,
+                REB_END.as_ptr());
+        }
+
+        native
+    }
+
+    /// Extracts the plain argument words from a Rebol function spec, e.g.
+    /// `"[n [integer!]]"` -> `["n"]`. Quoted docstrings (`"[\"Adds two
+    /// numbers\" a [integer!] b [integer!]]"` -> `["a", "b"]`) are skipped
+    /// rather than split on their interior whitespace, since otherwise
+    /// their words would be mistaken for argument names. This is a
+    /// lightweight heuristic over the common `word [type!]` shape, not a
+    /// full Rebol spec dialect parser.
+    fn parse_arg_names(spec: &str) -> Vec<CString> {
+        let mut names = Vec::new();
+        let mut depth = 0i32;
+        let mut word = String::new();
+        let mut in_quotes = false;
+        let flush = |word: &mut String, depth: i32, names: &mut Vec<CString>| {
+            if depth == 1 && !word.is_empty() {
+                if word.chars().next().map_or(false, |c| c.is_alphabetic() || c == '_') {
+                    names.push(CString::new(word.as_str()).unwrap());
                 }
-                Ok(RebValue {
-                    inner,
-                    engine: self
-                })
-            } else {
-                Ok(RebValue {
-                    inner: trapped,
-                    engine: self
-                })
+            }
+            word.clear();
+        };
+        for ch in spec.chars() {
+            match ch {
+                '"' => {
+                    if in_quotes {
+                        // A docstring, not an argument name: discard it
+                        // rather than flushing it as a candidate.
+                        word.clear();
+                        in_quotes = false;
+                    } else {
+                        flush(&mut word, depth, &mut names);
+                        in_quotes = true;
+                    }
+                }
+                '[' if !in_quotes => {
+                    flush(&mut word, depth, &mut names);
+                    depth += 1;
+                }
+                ']' if !in_quotes => {
+                    flush(&mut word, depth, &mut names);
+                    depth -= 1;
+                }
+                c if in_quotes => word.push(c),
+                c if c.is_whitespace() => flush(&mut word, depth, &mut names),
+                c => word.push(c),
             }
         }
+        flush(&mut word, depth, &mut names);
+        names
     }
 
-    pub fn value3<A, B, C>(&self, a: &A, b: &B, c: &C) -> RebValue
+    /// The single `extern "C"` trampoline installed for every native
+    /// registered via [`RebEngine::register`]. Ren-C calls this with the
+    /// live call `frame` and the `RebNative` context pointer leaked in
+    /// `register`; it recovers the closure, marshals each spec'd argument
+    /// into a `RebValue`, calls the closure, and hands the boxed result
+    /// back to Ren-C.
+    extern "C" fn native_trampoline(frame: *mut c_void, context: *mut c_void) -> *mut renc_sys::Reb_Value {
+        let native = unsafe {&*(context as *mut RebNative)};
+        let engine = unsafe {&*CURRENT_ENGINE.load(Ordering::SeqCst)};
+        let args: Vec<RebValue> = native.arg_names.iter()
+            .map(|name| unsafe {
+                RebValue::from_raw(engine, renc_sys::rebArg(frame, name.as_ptr()))
+            })
+            .collect();
+        let result = (native.f)(engine, &args);
+        result.into_raw()
+    }
+
+    /// Redirects Rebol's console output into `f` by rebinding `write-stdout`
+    /// (which `print` and friends funnel through) to a registered native
+    /// that forwards the spelled text to `f` instead of the process stdout.
+    /// The redirection stays in effect until [`RebEngine::reset_output`] is
+    /// called, or another `on_output` replaces it. See [`RebEngine::capture_output`]
+    /// for a scoped convenience built on top of this.
+    ///
+    /// Calling this repeatedly (the pattern `capture_output` relies on)
+    /// doesn't accumulate natives: the one superseded by a later call is
+    /// removed from the engine's native list and freed immediately rather
+    /// than being kept alive until the engine itself is dropped.
+    pub fn on_output<F>(&'a self, f: F)
     where
-        A: RebCode,
-        B: RebCode,
-        C: RebCode,
+        F: FnMut(&str) + 'static,
     {
-        let inner = unsafe {renc_sys::rebValue(a.as_const_ptr(),
-                                               b.as_const_ptr(),
-                                               c.as_const_ptr(),
-                                               REB_END.as_ptr())};
-        RebValue {
-            inner,
-            engine: self
+        if self.original_write_stdout.borrow().is_none() {
+            let original = unsafe {
+                renc_sys::rebValue(
+                    CUtf8::new("get 'write-stdout").as_const_ptr(),
+                    REB_END.as_ptr())
+            };
+            *self.original_write_stdout.borrow_mut() = Some(original);
+        }
+
+        let f = RefCell::new(f);
+        let native = self.register_native("write-stdout", "[value [text!]]", move |engine, args| {
+            let text = unsafe {args[0].unbox_string_q()};
+            (f.borrow_mut())(&text);
+            engine.void()
+        });
+
+        if let Some(previous) = self.active_output_native.borrow_mut().replace(native) {
+            self.natives.borrow_mut().retain(|&n| n != previous);
+            unsafe {drop(Box::from_raw(previous));}
         }
     }
 
-    pub fn elide<T: RebCode>(&self, t: &T) {
-        unsafe {renc_sys::rebElide(t.as_const_ptr(), REB_END.as_ptr())};
+    /// Restores `write-stdout` to whatever it was bound to before the first
+    /// [`RebEngine::on_output`] call. A no-op if output was never redirected.
+    pub fn reset_output(&self) {
+        if let Some(original) = self.original_write_stdout.borrow_mut().take() {
+            unsafe {
+                renc_sys::rebElide(
+                    CUtf8::new("set 'write-stdout").as_const_ptr(),
+                    original,
+                    REB_END.as_ptr());
+                renc_sys::rebRelease(original);
+            }
+        }
+    }
+
+    /// Runs `f`, collecting everything it prints via [`RebEngine::on_output`]
+    /// into a `String`, and restores normal output afterward.
+    pub fn capture_output<F: FnOnce()>(&'a self, f: F) -> String {
+        let captured = Rc::new(RefCell::new(String::new()));
+        let sink = captured.clone();
+        self.on_output(move |s| sink.borrow_mut().push_str(s));
+        f();
+        self.reset_output();
+        // `captured.borrow().clone()` can't be the tail expression here: its
+        // `Ref` temporary would otherwise outlive `captured` itself at the
+        // end of the block (E0597), since `captured` is a local about to be
+        // dropped. Binding the clone first drops the borrow before that happens.
+        let result = captured.borrow().clone();
+        result
     }
 }
 
@@ -336,6 +660,9 @@ impl<'a> RebValue<'a> {
     pub fn unbox_integer(&self) -> isize {
         unsafe {renc_sys::rebUnboxInteger(self.inner as *const c_void, REB_END.as_ptr())}
     }
+    pub fn unbox_decimal(&self) -> f64 {
+        unsafe {renc_sys::rebUnboxDecimal(self.inner as *const c_void, REB_END.as_ptr())}
+    }
     pub unsafe fn unbox_string(&self) -> String {
         let c = renc_sys::rebSpell(self.inner as *const c_void, REB_END.as_ptr());
         let r = CStr::from_ptr(c).to_str().unwrap().to_owned();
@@ -353,10 +680,229 @@ impl<'a> RebValue<'a> {
     unsafe fn from_raw(engine: &'a RebEngine, inner: *mut renc_sys::Reb_Value) -> Self
     {
         Self {
-            inner, 
+            inner,
             engine,
         }
     }
+
+    /// Consumes the `RebValue` without releasing it, handing ownership of
+    /// the underlying Ren-C value back across the FFI boundary (e.g. as a
+    /// native's return value, which Ren-C itself will manage from there).
+    fn into_raw(self) -> *mut renc_sys::Reb_Value {
+        let inner = self.inner;
+        mem::forget(self);
+        inner
+    }
+
+    /// Tests `self` against a Rebol type-checking predicate word such as
+    /// `"integer?"` or `"block?"`, used by the [`TryFromReb`] impls to
+    /// refuse a mismatched datatype instead of unboxing garbage.
+    fn is_datatype(&self, predicate: &str) -> bool {
+        unsafe {
+            renc_sys::rebDid(
+                CUtf8::new(predicate).as_const_ptr(),
+                self.inner,
+                REB_END.as_ptr())
+        }
+    }
+
+    /// Builds the [`RebError`] a [`TryFromReb`] impl returns when `self`
+    /// isn't the Rebol datatype it expected.
+    fn type_mismatch(&self, expected: &str) -> RebError {
+        RebError::RebError {
+            type_: RebErrorType::Script,
+            id: "expect-arg".to_owned(),
+            message: format!("expected a {} value", expected),
+            near: String::new(),
+            where_: String::new(),
+            file: String::new(),
+            line: 0,
+        }
+    }
+}
+
+/// Converts a Rust value into a [`RebValue`] living on a given engine.
+/// Implemented for the common scalar and collection types so users can
+/// hand plain Rust data to [`RebEngine::register`]ed closures or build up
+/// Rebol values without hand-rolling `rebInteger`/`rebText`/etc. calls.
+pub trait IntoReb {
+    fn into_reb<'a>(self, engine: &'a RebEngine) -> RebValue<'a>;
+}
+
+impl IntoReb for i64 {
+    fn into_reb<'a>(self, engine: &'a RebEngine) -> RebValue<'a> {
+        engine.integer(self)
+    }
+}
+
+impl IntoReb for f64 {
+    fn into_reb<'a>(self, engine: &'a RebEngine) -> RebValue<'a> {
+        engine.decimal(self)
+    }
+}
+
+impl IntoReb for char {
+    fn into_reb<'a>(self, engine: &'a RebEngine) -> RebValue<'a> {
+        engine.char(self)
+    }
+}
+
+impl IntoReb for bool {
+    fn into_reb<'a>(self, engine: &'a RebEngine) -> RebValue<'a> {
+        let word = if self {"true"} else {"false"};
+        reb_value!(engine; CUtf8::new(word)).unwrap()
+    }
+}
+
+impl IntoReb for &str {
+    fn into_reb<'a>(self, engine: &'a RebEngine) -> RebValue<'a> {
+        engine.text(self)
+    }
+}
+
+impl IntoReb for &[u8] {
+    fn into_reb<'a>(self, engine: &'a RebEngine) -> RebValue<'a> {
+        engine.sized_binary(self)
+    }
+}
+
+impl<T: IntoReb> IntoReb for Option<T> {
+    fn into_reb<'a>(self, engine: &'a RebEngine) -> RebValue<'a> {
+        match self {
+            Some(v) => v.into_reb(engine),
+            None => engine.null(),
+        }
+    }
+}
+
+impl<T: IntoReb> IntoReb for Vec<T> {
+    fn into_reb<'a>(self, engine: &'a RebEngine) -> RebValue<'a> {
+        let block = unsafe {
+            RebValue::from_raw(engine, renc_sys::rebValue(
+                CUtf8::new("make block!").as_const_ptr(),
+                engine.integer(self.len() as i64).as_const_ptr(),
+                REB_END.as_ptr()))
+        };
+        for item in self {
+            let v = item.into_reb(engine);
+            unsafe {
+                renc_sys::rebElide(
+                    CUtf8::new("append").as_const_ptr(),
+                    block.as_const_ptr(),
+                    v.as_const_ptr(),
+                    REB_END.as_ptr());
+            }
+        }
+        block
+    }
+}
+
+/// Converts a [`RebValue`] into a Rust value, checking the Rebol datatype
+/// first and returning `Err(RebError)` on a mismatch instead of panicking
+/// like the raw `unbox_*` methods do.
+pub trait TryFromReb<'a>: Sized {
+    fn try_from_reb(v: &RebValue<'a>) -> Result<Self, RebError>;
+}
+
+impl<'a> TryFromReb<'a> for i64 {
+    fn try_from_reb(v: &RebValue<'a>) -> Result<Self, RebError> {
+        if v.is_datatype("integer?") {
+            Ok(v.unbox_integer() as i64)
+        } else {
+            Err(v.type_mismatch("integer!"))
+        }
+    }
+}
+
+impl<'a> TryFromReb<'a> for f64 {
+    fn try_from_reb(v: &RebValue<'a>) -> Result<Self, RebError> {
+        if v.is_datatype("decimal?") {
+            Ok(v.unbox_decimal())
+        } else {
+            Err(v.type_mismatch("decimal!"))
+        }
+    }
+}
+
+impl<'a> TryFromReb<'a> for bool {
+    fn try_from_reb(v: &RebValue<'a>) -> Result<Self, RebError> {
+        if v.is_datatype("logic?") {
+            Ok(unsafe {renc_sys::rebDid(v.inner as *const c_void, REB_END.as_ptr())})
+        } else {
+            Err(v.type_mismatch("logic!"))
+        }
+    }
+}
+
+impl<'a> TryFromReb<'a> for String {
+    fn try_from_reb(v: &RebValue<'a>) -> Result<Self, RebError> {
+        if v.is_datatype("text?") {
+            Ok(unsafe {v.unbox_string()})
+        } else {
+            Err(v.type_mismatch("text!"))
+        }
+    }
+}
+
+impl<'a> TryFromReb<'a> for Vec<RebValue<'a>> {
+    fn try_from_reb(v: &RebValue<'a>) -> Result<Self, RebError> {
+        if !v.is_datatype("block?") {
+            return Err(v.type_mismatch("block!"));
+        }
+        let len = unsafe {
+            RebValue::from_raw(v.engine, renc_sys::rebValue(
+                CUtf8::new("length of").as_const_ptr(),
+                v.inner,
+                REB_END.as_ptr()))
+        }.unbox_integer();
+        let mut items = Vec::with_capacity(len as usize);
+        for i in 1..=len {
+            let item = unsafe {
+                RebValue::from_raw(v.engine, renc_sys::rebValue(
+                    CUtf8::new("pick").as_const_ptr(),
+                    v.inner,
+                    v.engine.integer(i as i64).as_const_ptr(),
+                    REB_END.as_ptr()))
+            };
+            items.push(item);
+        }
+        Ok(items)
+    }
+}
+
+impl<'a> TryFrom<&RebValue<'a>> for i64 {
+    type Error = RebError;
+    fn try_from(v: &RebValue<'a>) -> Result<Self, RebError> {
+        TryFromReb::try_from_reb(v)
+    }
+}
+
+impl<'a> TryFrom<&RebValue<'a>> for f64 {
+    type Error = RebError;
+    fn try_from(v: &RebValue<'a>) -> Result<Self, RebError> {
+        TryFromReb::try_from_reb(v)
+    }
+}
+
+impl<'a> TryFrom<&RebValue<'a>> for bool {
+    type Error = RebError;
+    fn try_from(v: &RebValue<'a>) -> Result<Self, RebError> {
+        TryFromReb::try_from_reb(v)
+    }
+}
+
+impl<'a> TryFrom<&RebValue<'a>> for String {
+    type Error = RebError;
+    fn try_from(v: &RebValue<'a>) -> Result<Self, RebError> {
+        TryFromReb::try_from_reb(v)
+    }
+}
+
+impl<'a> TryFrom<&RebValue<'a>> for Vec<RebValue<'a>> {
+    type Error = RebError;
+    fn try_from(v: &RebValue<'a>) -> Result<Self, RebError> {
+        TryFromReb::try_from_reb(v)
+    }
 }
 
 
@@ -383,6 +929,13 @@ impl RebCode for CUtf8 {
 impl Drop for RebEngine {
     fn drop(&mut self) {
         trace!("dropping a rebengine");
+        for native in self.natives.borrow_mut().drain(..) {
+            unsafe {drop(Box::from_raw(native));}
+        }
+        if let Some(original) = self.original_write_stdout.borrow_mut().take() {
+            unsafe {renc_sys::rebRelease(original);}
+        }
+        CURRENT_ENGINE.store(ptr::null_mut(), Ordering::SeqCst);
         unsafe{renc_sys::rebShutdown(true);}
         if ! REB_STARTED_UP.swap(false, Ordering::SeqCst) {
             panic!("Renc engine is not running in this thread");
@@ -390,13 +943,135 @@ impl Drop for RebEngine {
     }
 }
 
-/*
-macro_rule! evaluate {
-    ($engine:expr, $($arg:expr),+) => {
-        renc_sys::rebValue($($arg),+, REB_END.as_ptr())
+/// An owned, engine-free snapshot of a [`RebValue`], safe to move off the
+/// interpreter's thread (a borrowed `RebValue` isn't `Send`). [`RebWorker`]
+/// converts into this before handing a result back across its channel.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OwnedRebResult {
+    Integer(i64),
+    Decimal(f64),
+    Text(String),
+    /// The `mold`ed source text of any datatype not special-cased above.
+    Molded(String),
+}
+
+impl OwnedRebResult {
+    fn from_reb_value(v: &RebValue) -> Self {
+        if let Ok(i) = i64::try_from_reb(v) {
+            OwnedRebResult::Integer(i)
+        } else if let Ok(d) = f64::try_from_reb(v) {
+            OwnedRebResult::Decimal(d)
+        } else if let Ok(s) = String::try_from_reb(v) {
+            OwnedRebResult::Text(s)
+        } else {
+            OwnedRebResult::Molded(v.engine.mold(v))
+        }
+    }
+}
+
+/// The shared state behind [`oneshot`], a single-value, single-waker
+/// channel with just enough of `Future` wired up to `.await` a result
+/// computed on [`RebWorker`]'s dedicated thread.
+struct OneshotState<T> {
+    value: Mutex<Option<T>>,
+    waker: Mutex<Option<Waker>>,
+}
+
+struct OneshotSender<T> {
+    state: Arc<OneshotState<T>>,
+}
+
+pub struct OneshotReceiver<T> {
+    state: Arc<OneshotState<T>>,
+}
+
+fn oneshot<T>() -> (OneshotSender<T>, OneshotReceiver<T>) {
+    let state = Arc::new(OneshotState {
+        value: Mutex::new(None),
+        waker: Mutex::new(None),
+    });
+    (OneshotSender {state: state.clone()}, OneshotReceiver {state})
+}
+
+impl<T> OneshotSender<T> {
+    fn send(self, value: T) {
+        *self.state.value.lock().unwrap() = Some(value);
+        if let Some(waker) = self.state.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Future for OneshotReceiver<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        let mut value = self.state.value.lock().unwrap();
+        if let Some(v) = value.take() {
+            Poll::Ready(v)
+        } else {
+            *self.state.waker.lock().unwrap() = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+struct RebWorkerJob {
+    code: String,
+    respond_to: OneshotSender<Result<OwnedRebResult, RebError>>,
+}
+
+/// Owns a single [`RebEngine`] on a dedicated OS thread, so code that needs
+/// to `.await` Rebol evaluation doesn't have to pin its executor to that
+/// thread itself (the interpreter, via `REB_STARTED_UP`, is pinned to
+/// whichever thread starts it up). Each [`RebWorker::eval`] call ships a
+/// code fragment over a channel and awaits its result via a one-shot
+/// channel; because `RebValue` borrows the engine and isn't `Send`, the
+/// worker converts its result to an [`OwnedRebResult`] before it crosses
+/// back over the channel.
+pub struct RebWorker {
+    sender: Option<mpsc::Sender<RebWorkerJob>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl RebWorker {
+    pub fn spawn() -> Self {
+        let (sender, jobs) = mpsc::channel::<RebWorkerJob>();
+        let thread = thread::spawn(move || {
+            let engine = RebEngine::new();
+            for job in jobs {
+                let result = reb_value!(engine; CUtf8::new(&job.code))
+                    .map(|v| OwnedRebResult::from_reb_value(&v));
+                job.respond_to.send(result);
+            }
+        });
+        RebWorker {
+            sender: Some(sender),
+            thread: Some(thread),
+        }
+    }
+
+    pub async fn eval(&self, code: String) -> Result<OwnedRebResult, RebError> {
+        let (respond_to, result) = oneshot();
+        self.sender.as_ref()
+            .expect("RebWorker's thread has already been shut down")
+            .send(RebWorkerJob {code, respond_to})
+            .expect("RebWorker's thread has already shut down");
+        result.await
+    }
+}
+
+impl Drop for RebWorker {
+    fn drop(&mut self) {
+        // Drop the sender first so the worker thread's `for job in jobs`
+        // loop sees the channel disconnect and exits, making the join
+        // below terminate instead of blocking forever.
+        drop(self.sender.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
     }
 }
-*/
 
 #[cfg(test)]
 mod tests {
@@ -415,6 +1090,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn one_plus_one_v0() {
         let engine = RebEngine::new();
         let two = match engine.value1(&CUtf8::new("1 + 1")) {
@@ -428,6 +1104,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn one_plus_one_v1() {
         let engine = RebEngine::new();
         let two = engine.value2(&CUtf8::new("1 + "), &engine.integer(1)).unwrap();
@@ -435,13 +1112,39 @@ mod tests {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn one_plus_one_v2() {
         let engine = RebEngine::new();
         let one = engine.integer(1);
-        let two = engine.value3(&one, &CUtf8::new("+"), &one);
+        let two = engine.value3(&one, &CUtf8::new("+"), &one).unwrap();
         assert_eq!(2, two.unbox_integer());
     }
 
+    #[test]
+    fn one_plus_one_reb_value() {
+        let engine = RebEngine::new();
+        let one = engine.integer(1);
+        let two = reb_value!(engine; one, CUtf8::new("+"), one).unwrap();
+        assert_eq!(2, two.unbox_integer());
+    }
+
+    #[test]
+    fn reb_value_beyond_three_fragments() {
+        let engine = RebEngine::new();
+        let one = engine.integer(1);
+        let sum = reb_value!(
+            engine;
+            one, CUtf8::new("+"), one, CUtf8::new("+"), one, CUtf8::new("+"), one
+        ).unwrap();
+        assert_eq!(4, sum.unbox_integer());
+    }
+
+    #[test]
+    fn reb_elide_hello_world() {
+        let engine = RebEngine::new();
+        reb_elide!(engine; CUtf8::new("print"), CUtf8::new(r##""hello, reb_elide!""##));
+    }
+
     #[test]
     fn hello_world() {
         let engine = RebEngine::new();
@@ -449,6 +1152,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn func_call() {
         let engine = RebEngine::new();
         let fib_str = CUtf8::new(r##"
@@ -503,10 +1207,138 @@ mod tests {
     }
 
     #[test]
+    fn register_native() {
+        let engine = RebEngine::new();
+        engine.register("rust-double", "[n [integer!]]", |engine, args| {
+            engine.integer(2 * args[0].unbox_integer() as i64)
+        });
+        let six = reb_value!(engine; CUtf8::new("rust-double 3")).unwrap();
+        assert_eq!(6, six.unbox_integer());
+    }
+
+    #[test]
+    fn register_native_with_docstring_spec() {
+        let engine = RebEngine::new();
+        engine.register(
+            "rust-add",
+            "[\"Adds two numbers\" a [integer!] b [integer!]]",
+            |engine, args| {
+                engine.integer(args[0].unbox_integer() as i64 + args[1].unbox_integer() as i64)
+            });
+        let seven = reb_value!(engine; CUtf8::new("rust-add 3 4")).unwrap();
+        assert_eq!(7, seven.unbox_integer());
+    }
+
+    #[test]
+    fn parse_arg_names_skips_docstring() {
+        let names = RebEngine::parse_arg_names("[\"Adds two numbers\" a [integer!] b [integer!]]");
+        assert_eq!(
+            vec!["a", "b"],
+            names.iter().map(|n| n.to_str().unwrap()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn capture_output_test() {
+        let engine = RebEngine::new();
+        let printed = engine.capture_output(|| {
+            engine.elide(&CUtf8::new(r##"print "hi""##));
+        });
+        assert_eq!("hi\n", printed);
+    }
+
+    #[test]
+    #[allow(deprecated)]
     fn hello_error() {
         let engine = RebEngine::new();
         let e = engine.value1(&CUtf8::new("1 / 0"));
         //println!("e: {:?}", e);
         assert!(e.is_err());
     }
+
+    #[test]
+    #[allow(deprecated)]
+    fn error_type_is_categorized() {
+        let engine = RebEngine::new();
+        let err = engine.value1(&CUtf8::new("1 / 0")).unwrap_err();
+        match err {
+            RebError::RebError {type_: RebErrorType::Math, line, ..} => {
+                assert!(line > 0);
+            }
+            other => panic!("expected a categorized Math error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn into_reb_scalars() {
+        let engine = RebEngine::new();
+        assert_eq!(42, 42i64.into_reb(&engine).unbox_integer());
+        assert_eq!("hi", unsafe {"hi".into_reb(&engine).unbox_string()});
+    }
+
+    #[test]
+    fn into_reb_vec_round_trips_through_try_from_reb() {
+        let engine = RebEngine::new();
+        let block = vec![1i64, 2, 3].into_reb(&engine);
+        let items = Vec::<RebValue>::try_from(&block).unwrap();
+        assert_eq!(3, items.len());
+        assert_eq!(1, items[0].unbox_integer());
+        assert_eq!(3, items[2].unbox_integer());
+    }
+
+    #[test]
+    fn try_from_reb_rejects_mismatched_datatype() {
+        let engine = RebEngine::new();
+        let one = engine.integer(1);
+        let err = String::try_from(&one);
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn eval_with_timeout_completes_within_budget() {
+        let engine = RebEngine::new();
+        let two = engine.eval_with_timeout("1 + 1", Duration::from_secs(5)).unwrap();
+        assert_eq!(2, two.unbox_integer());
+    }
+
+    #[test]
+    fn eval_with_timeout_halts_a_runaway_loop() {
+        let engine = RebEngine::new();
+        let err = engine.eval_with_timeout(
+            "forever [1 + 1]",
+            Duration::from_millis(50),
+        ).unwrap_err();
+        match err {
+            RebError::RebError {type_: RebErrorType::Interrupted(_), ..} => {}
+            other => panic!("expected an Interrupted error, got {:?}", other),
+        }
+    }
+
+    /// A minimal spin-poll executor, just enough to drive a single future
+    /// to completion in a test without pulling in an async runtime.
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        use std::task::{RawWaker, RawWakerVTable};
+
+        fn no_op(_: *const ()) {}
+        fn clone(_: *const ()) -> RawWaker {
+            RawWaker::new(ptr::null(), &VTABLE)
+        }
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, no_op, no_op, no_op);
+        let waker = unsafe {Waker::from_raw(RawWaker::new(ptr::null(), &VTABLE))};
+        let mut cx = Context::from_waker(&waker);
+
+        let mut fut = Box::pin(fut);
+        loop {
+            match fut.as_mut().poll(&mut cx) {
+                Poll::Ready(v) => return v,
+                Poll::Pending => thread::yield_now(),
+            }
+        }
+    }
+
+    #[test]
+    fn reb_worker_evaluates_off_thread() {
+        let worker = RebWorker::spawn();
+        let result = block_on(worker.eval("1 + 1".to_owned())).unwrap();
+        assert_eq!(OwnedRebResult::Integer(2), result);
+    }
 }